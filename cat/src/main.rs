@@ -1,10 +1,25 @@
 use std::env;
 use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::process;
 
-struct Config(Vec<String>, Box<dyn io::Write>);
+#[cfg(unix)]
+use std::io::IsTerminal;
+#[cfg(unix)]
+use std::net::Shutdown;
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::io::{AsRawFd, RawFd};
+#[cfg(unix)]
+use std::os::unix::net::UnixStream;
+
+#[cfg(any(target_os = "linux", target_os = "android"))]
+mod splice;
+
+struct Config(Vec<String>, Box<dyn SpliceableWrite>);
 
 fn main() -> Result<(), String> {
     let Config(ref files, ref mut output) = process_args()?;
@@ -12,29 +27,68 @@ fn main() -> Result<(), String> {
     Ok(())
 }
 
+// Numbering controls which lines get a leading line number printed in
+// front of them, mirroring GNU cat's -n/-b flags.
+#[derive(PartialEq, Eq)]
+enum Numbering {
+    None,
+    All,
+    NonBlank,
+}
+
 fn process_args() -> Result<Config, String> {
     let is_flag = |a: &String| a.len() >= 2 && a.bytes().next().unwrap() == b'-';
     let flags: Vec<String> = env::args().skip(1).filter(is_flag).collect();
     let non_flags: Vec<String> = env::args().skip(1).filter(|a| !is_flag(a)).collect();
 
-    let mut show_line_numbers: bool = false;
+    let mut opts = OutputOptions::new();
+    // -n and -b are tracked independently, like GNU cat's number/
+    // number_nonblank, so that -b always wins regardless of whether it
+    // comes before or after -n on the command line.
+    let mut number_all = false;
+    let mut number_nonblank = false;
     for flag in flags {
         match flag.as_str() {
-            "-n" => show_line_numbers = true,
+            "-n" => number_all = true,
+            "-b" => number_nonblank = true,
+            "-E" | "--show-ends" => opts.show_ends = true,
+            "-T" | "--show-tabs" => opts.show_tabs = true,
+            "-s" | "--squeeze-blank" => opts.squeeze_blank = true,
+            "-v" | "--show-nonprinting" => opts.show_nonprinting = true,
+            "-A" => {
+                opts.show_nonprinting = true;
+                opts.show_ends = true;
+                opts.show_tabs = true;
+            }
+            "-e" => {
+                opts.show_nonprinting = true;
+                opts.show_ends = true;
+            }
+            "-t" => {
+                opts.show_nonprinting = true;
+                opts.show_tabs = true;
+            }
             "-h" => {
                 return Err(format!(
-                    "Usage: {} [-n] [file1 [file2 ...]]",
+                    "Usage: {} [-AbeEnstTv] [file1 [file2 ...]]",
                     env::args().next().unwrap_or_else(|| "cat".to_string())
                 ))
             }
             _ => return Err(format!("Unrecognized flag {}", flag)),
         }
     }
-
-    let output: Box<dyn io::Write> = if show_line_numbers {
-        Box::new(NumberedOut::new())
+    opts.numbering = if number_nonblank {
+        Numbering::NonBlank
+    } else if number_all {
+        Numbering::All
     } else {
+        Numbering::None
+    };
+
+    let output: Box<dyn SpliceableWrite> = if opts.is_identity() {
         Box::new(io::stdout())
+    } else {
+        Box::new(opts)
     };
 
     let files = if non_flags.is_empty() {
@@ -47,13 +101,13 @@ fn process_args() -> Result<Config, String> {
 
 // process_files reads each file listed, and writes  the contents to output.
 // The special filename "-" is treated as meaning stdin.
-fn process_files(files: &[String], output: &mut dyn io::Write) {
+fn process_files(files: &[String], output: &mut dyn SpliceableWrite) {
     let mut exit_status = 0;
     for file in files {
         let result = if file == "-" {
             copy_file_to("-", &mut io::stdin(), output)
         } else {
-            copy_to(&file, output)
+            copy_to(file, output)
         };
         match result {
             Ok(()) => continue,
@@ -64,7 +118,10 @@ fn process_files(files: &[String], output: &mut dyn io::Write) {
                 // buffering means much of the regular output will get
                 // displayed after the error, even if it was output before
                 // the error.
-                output.flush().unwrap();
+                if let Err(flush_err) = output.flush() {
+                    exit_if_broken_pipe(&flush_err);
+                    panic!("failed to flush output: {}", flush_err);
+                }
                 eprintln!("{}", e);
                 // Don't exit immediately on error. Try to read any
                 // remaining files. Mimics GNU cat.
@@ -72,79 +129,309 @@ fn process_files(files: &[String], output: &mut dyn io::Write) {
             }
         }
     }
-    output.flush().expect("failed to flush output");
+    if let Err(e) = output.flush() {
+        exit_if_broken_pipe(&e);
+        panic!("failed to flush output: {}", e);
+    }
     process::exit(exit_status);
 }
 
-// NumberedOut implements Write by writing output to stdout, prefixed by
-// line numbers (starting with 1). Line numbers are only printed when there
-// are more bytes to print after them, so a file that ends in a newline
-// won't have an additional number printed after the last line.
-struct NumberedOut {
+// exit_if_broken_pipe terminates the process cleanly, as if we'd finished
+// normally, when the downstream reader of our output has gone away (e.g.
+// `learnrust cat bigfile | head`). Any other I/O error is left for the
+// caller to handle.
+fn exit_if_broken_pipe(e: &io::Error) {
+    if e.kind() == io::ErrorKind::BrokenPipe {
+        exit_cleanly();
+    }
+}
+
+fn exit_cleanly() -> ! {
+    process::exit(0);
+}
+
+// OutputOptions drives a byte-stream state machine that applies every
+// line-transform flag cat supports (-n/-b numbering, -E show-ends, -T
+// show-tabs, -s squeeze-blank and -v show-nonprinting, plus the -A/-e/-t
+// combinations of those). Every flag stacks on the same byte stream, so
+// rather than wrapping one `Box<dyn Write>` per flag, a single
+// OutputOptions tracks all the state needed (current line number,
+// beginning-of-line, previous-line-blank) and applies every requested
+// transform as bytes pass through.
+struct OutputOptions {
+    numbering: Numbering,
+    show_ends: bool,
+    show_tabs: bool,
+    squeeze_blank: bool,
+    show_nonprinting: bool,
+
     n: i64,
     beginning_line: bool,
+    prev_line_blank: bool,
     output: Box<dyn io::Write>,
 }
-impl NumberedOut {
-    fn new() -> NumberedOut {
-        NumberedOut {
+
+impl OutputOptions {
+    fn new() -> OutputOptions {
+        OutputOptions {
+            numbering: Numbering::None,
+            show_ends: false,
+            show_tabs: false,
+            squeeze_blank: false,
+            show_nonprinting: false,
+
             n: 0,
             beginning_line: true,
+            prev_line_blank: false,
             output: Box::new(io::BufWriter::new(io::stdout())),
         }
     }
 
-    fn print_number(&mut self) -> Result<usize, io::Error> {
+    // is_identity reports whether none of the flags would change the
+    // output at all, so the caller can skip this state machine entirely
+    // and write straight to stdout.
+    fn is_identity(&self) -> bool {
+        self.numbering == Numbering::None
+            && !self.show_ends
+            && !self.show_tabs
+            && !self.squeeze_blank
+            && !self.show_nonprinting
+    }
+
+    fn print_number(&mut self) -> Result<(), io::Error> {
         self.n += 1;
-        self.beginning_line = true;
-        self.output.write(format!("{:6} ", self.n).as_bytes())
+        self.output.write_all(format!("{:6} ", self.n).as_bytes())
+    }
+
+    // render writes a single non-newline byte, applying -T and -v.
+    fn render(&mut self, byte: u8) -> Result<(), io::Error> {
+        if byte == b'\t' {
+            return if self.show_tabs {
+                self.output.write_all(b"^I")
+            } else {
+                self.output.write_all(&[byte])
+            };
+        }
+        if !self.show_nonprinting {
+            return self.output.write_all(&[byte]);
+        }
+        if byte >= 128 {
+            self.output.write_all(b"M-")?;
+            self.render_control(byte & 0x7f)
+        } else {
+            self.render_control(byte)
+        }
+    }
+
+    // render_control renders a byte in 0..128 using the caret notation
+    // -v uses for control characters and DEL, leaving everything else
+    // untouched.
+    fn render_control(&mut self, byte: u8) -> Result<(), io::Error> {
+        if byte < 32 {
+            self.output.write_all(&[b'^', byte ^ 0x40])
+        } else if byte == 127 {
+            self.output.write_all(b"^?")
+        } else {
+            self.output.write_all(&[byte])
+        }
     }
 }
-impl io::Write for NumberedOut {
+
+impl io::Write for OutputOptions {
     fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
-        for byte in buf {
+        for &byte in buf {
             if self.beginning_line {
-                self.print_number()?;
-            }
-            if *byte == b'\n' {
-                self.beginning_line = true;
-            } else {
+                let blank_line = byte == b'\n';
+                if self.squeeze_blank && blank_line && self.prev_line_blank {
+                    // Collapse this blank line away entirely: don't
+                    // number it, don't show-ends it, just drop it.
+                    continue;
+                }
+                self.prev_line_blank = blank_line;
+
+                let should_number = match self.numbering {
+                    Numbering::None => false,
+                    Numbering::All => true,
+                    Numbering::NonBlank => !blank_line,
+                };
+                if should_number {
+                    self.print_number()?;
+                }
                 self.beginning_line = false;
             }
 
-            self.output.write_all(&[*byte][..])?;
-
-            if *byte == b'\n' {
+            if byte == b'\n' {
+                if self.show_ends {
+                    self.output.write_all(b"$")?;
+                }
+                self.output.write_all(b"\n")?;
+                self.beginning_line = true;
                 // Flush at the end of each line so if the user is
-                // typing input on stdin, they see the numbered output
-                // right away (even though output is buffered).
+                // typing input on stdin, they see the transformed
+                // output right away (even though output is buffered).
                 // Mimics GNU cat.
                 self.output.flush()?;
+            } else {
+                self.render(byte)?;
             }
         }
         Ok(buf.len())
     }
+
     fn flush(&mut self) -> Result<(), io::Error> {
         self.output.flush()
     }
 }
 
-// copy_to opens a file and copies it to the provided output.
-fn copy_to(filename: &str, output: &mut dyn io::Write) -> Result<(), CatError> {
-    match File::open(filename) {
-        Ok(mut file) => copy_file_to(filename, &mut file, output),
-        Err(e) => Err(CatError {
-            filename: filename.to_string(),
-            message: e.to_string(),
-        }),
+#[cfg(unix)]
+type Fd = RawFd;
+#[cfg(not(unix))]
+type Fd = i32;
+
+// SpliceableRead and SpliceableWrite let copy_file_to recover the raw
+// file descriptor behind a reader/writer, when it has one, so the Linux
+// splice(2) fast path can bypass the userspace buffer io::copy uses.
+// Types with no meaningful fd (OutputOptions transforms the byte
+// stream, so it must never be spliced) just keep the default `None` and
+// always fall back to io::copy.
+trait SpliceableRead: io::Read {
+    fn splice_fd(&self) -> Option<Fd> {
+        None
+    }
+}
+trait SpliceableWrite: io::Write {
+    fn splice_fd(&self) -> Option<Fd> {
+        None
     }
 }
 
+// A boxed SpliceableWrite is itself SpliceableWrite, same as Box<dyn
+// Write> is Write in std, so the boxed output in Config can be passed
+// straight to functions expecting `&mut dyn SpliceableWrite`.
+impl<W: SpliceableWrite + ?Sized> SpliceableWrite for Box<W> {
+    fn splice_fd(&self) -> Option<Fd> {
+        (**self).splice_fd()
+    }
+}
+
+#[cfg(unix)]
+impl SpliceableRead for File {
+    fn splice_fd(&self) -> Option<RawFd> {
+        Some(self.as_raw_fd())
+    }
+}
+#[cfg(not(unix))]
+impl SpliceableRead for File {}
+
+// splice(2) can't move data out of or into a terminal, so stdin/stdout
+// only advertise a splice-able fd when they aren't connected to one.
+#[cfg(unix)]
+impl SpliceableRead for io::Stdin {
+    fn splice_fd(&self) -> Option<RawFd> {
+        if self.is_terminal() {
+            None
+        } else {
+            Some(self.as_raw_fd())
+        }
+    }
+}
+#[cfg(not(unix))]
+impl SpliceableRead for io::Stdin {}
+
+#[cfg(unix)]
+impl SpliceableRead for UnixStream {
+    fn splice_fd(&self) -> Option<RawFd> {
+        Some(self.as_raw_fd())
+    }
+}
+
+impl SpliceableWrite for OutputOptions {}
+
+#[cfg(unix)]
+impl SpliceableWrite for io::Stdout {
+    fn splice_fd(&self) -> Option<RawFd> {
+        if self.is_terminal() {
+            None
+        } else {
+            Some(self.as_raw_fd())
+        }
+    }
+}
+#[cfg(not(unix))]
+impl SpliceableWrite for io::Stdout {}
+
+#[cfg(unix)]
+impl SpliceableWrite for File {
+    fn splice_fd(&self) -> Option<RawFd> {
+        Some(self.as_raw_fd())
+    }
+}
+#[cfg(not(unix))]
+impl SpliceableWrite for File {}
+
+// copy_to classifies filename by its file type and dispatches to the
+// right way of reading it: directories are rejected outright, Unix
+// sockets are connected to, regular files, fifos and block/char devices
+// are opened and copied like a regular file, and any other file type is
+// rejected as an unknown filetype.
+fn copy_to(filename: &str, output: &mut dyn SpliceableWrite) -> Result<(), CatError> {
+    let metadata = fs::metadata(filename).map_err(|e| CatError::new(filename, e))?;
+    let file_type = metadata.file_type();
+
+    if file_type.is_dir() {
+        return Err(CatError::new(filename, "Is a directory"));
+    }
+
+    #[cfg(unix)]
+    {
+        if file_type.is_socket() {
+            return copy_socket_to(filename, output);
+        }
+        if !(file_type.is_file()
+            || file_type.is_fifo()
+            || file_type.is_block_device()
+            || file_type.is_char_device())
+        {
+            return Err(CatError::new(filename, "unknown filetype"));
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        if !file_type.is_file() {
+            return Err(CatError::new(filename, "unknown filetype"));
+        }
+    }
+
+    let mut file = File::open(filename).map_err(|e| CatError::new(filename, e))?;
+    copy_file_to(filename, &mut file, output)
+}
+
+// copy_socket_to connects to a Unix domain socket, signals that we won't
+// be writing anything to it, and copies whatever it streams back to us
+// into output.
+#[cfg(unix)]
+fn copy_socket_to(filename: &str, output: &mut dyn SpliceableWrite) -> Result<(), CatError> {
+    let mut stream = UnixStream::connect(filename).map_err(|e| CatError::new(filename, e))?;
+    stream
+        .shutdown(Shutdown::Write)
+        .map_err(|e| CatError::new(filename, e))?;
+    copy_file_to(filename, &mut stream, output)
+}
+
 struct CatError {
     filename: String,
     message: String,
 }
 
+impl CatError {
+    fn new(filename: &str, err: impl fmt::Display) -> CatError {
+        CatError {
+            filename: filename.to_string(),
+            message: err.to_string(),
+        }
+    }
+}
+
 impl fmt::Display for CatError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "{}: {}", self.filename, self.message)
@@ -152,17 +439,136 @@ impl fmt::Display for CatError {
 }
 
 // copy_file_to copies bytes from the provided Read object to a Write object.
-// Errors will be prefixed with the provided filename.
+// Errors will be prefixed with the provided filename. A broken pipe on
+// the output side (the downstream reader of a shell pipeline went away)
+// is not an error: it ends the process cleanly instead, the same way GNU
+// cat does.
+//
+// On Linux and Android, when both sides are backed by a real file
+// descriptor, this first tries the zero-copy splice(2) fast path (see
+// the `splice` module) before falling back to io::copy.
 fn copy_file_to(
     filename: &str,
-    input: &mut dyn io::Read,
-    output: &mut dyn io::Write,
+    input: &mut dyn SpliceableRead,
+    output: &mut dyn SpliceableWrite,
 ) -> Result<(), CatError> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    {
+        if let (Some(in_fd), Some(out_fd)) = (input.splice_fd(), output.splice_fd()) {
+            match splice::try_splice(in_fd, out_fd) {
+                Ok(true) => return Ok(()),
+                // The kernel doesn't support splicing this pair of fds;
+                // fall back to the portable io::copy path below.
+                Ok(false) => {}
+                Err(e) => {
+                    exit_if_broken_pipe(&e);
+                    return Err(CatError::new(filename, e));
+                }
+            }
+        }
+    }
+
     match io::copy(input, output) {
         Ok(_) => Ok(()),
-        Err(e) => Err(CatError {
-            filename: filename.to_string(),
-            message: e.to_string(),
-        }),
+        Err(e) => {
+            exit_if_broken_pipe(&e);
+            Err(CatError::new(filename, e))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::io::Write;
+    use std::rc::Rc;
+
+    // SharedBuf is a Write sink cheap to clone, so a test can hand one
+    // half to OutputOptions and keep the other half around to inspect
+    // what was written.
+    #[derive(Clone)]
+    struct SharedBuf(Rc<RefCell<Vec<u8>>>);
+
+    impl SharedBuf {
+        fn new() -> SharedBuf {
+            SharedBuf(Rc::new(RefCell::new(Vec::new())))
+        }
+
+        fn contents(&self) -> Vec<u8> {
+            self.0.borrow().clone()
+        }
+    }
+
+    impl io::Write for SharedBuf {
+        fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> Result<(), io::Error> {
+            Ok(())
+        }
+    }
+
+    fn output_options(
+        numbering: Numbering,
+        show_ends: bool,
+        show_tabs: bool,
+        squeeze_blank: bool,
+        show_nonprinting: bool,
+    ) -> (OutputOptions, SharedBuf) {
+        let buf = SharedBuf::new();
+        let opts = OutputOptions {
+            numbering,
+            show_ends,
+            show_tabs,
+            squeeze_blank,
+            show_nonprinting,
+            n: 0,
+            beginning_line: true,
+            prev_line_blank: false,
+            output: Box::new(buf.clone()),
+        };
+        (opts, buf)
+    }
+
+    #[test]
+    fn numbers_every_line_with_n() {
+        let (mut opts, buf) = output_options(Numbering::All, false, false, false, false);
+        opts.write_all(b"a\nb\n").unwrap();
+        assert_eq!(buf.contents(), b"     1 a\n     2 b\n");
+    }
+
+    #[test]
+    fn b_numbers_non_blank_lines_only() {
+        // This is also the precedence process_args resolves: -b wins
+        // over -n regardless of argument order.
+        let (mut opts, buf) = output_options(Numbering::NonBlank, false, false, false, false);
+        opts.write_all(b"a\n\nb\n").unwrap();
+        assert_eq!(buf.contents(), b"     1 a\n\n     2 b\n");
+    }
+
+    #[test]
+    fn squeeze_blank_collapses_runs_across_writes() {
+        let (mut opts, buf) = output_options(Numbering::None, false, false, true, false);
+        opts.write_all(b"a\n\n\n").unwrap();
+        opts.write_all(b"\nb\n").unwrap();
+        assert_eq!(buf.contents(), b"a\n\nb\n");
+    }
+
+    #[test]
+    fn show_nonprinting_renders_tab_control_del_and_high_bit() {
+        let (mut opts, buf) = output_options(Numbering::None, false, true, false, true);
+        opts.write_all(&[b'\t', 1, 127, 0xC1]).unwrap();
+        // \t -> ^I (via -T), 0x01 -> ^A, DEL -> ^?, 0xC1 (0x80 | 'A') -> M-A
+        assert_eq!(buf.contents(), b"^I^A^?M-A");
+    }
+
+    #[test]
+    fn a_combines_show_nonprinting_show_ends_and_show_tabs() {
+        let (mut opts, buf) = output_options(Numbering::None, true, true, false, true);
+        opts.write_all(b"x\ty\n").unwrap();
+        assert_eq!(buf.contents(), b"x^Iy$\n");
     }
 }
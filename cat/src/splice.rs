@@ -0,0 +1,144 @@
+// Zero-copy fast path for copying between two file descriptors on
+// Linux/Android, mirroring uutils' `splice` module. `splice(2)` moves
+// data directly between file descriptors inside the kernel, so plain
+// files and pipes never have to pass through a userspace buffer the
+// way `io::copy` requires.
+
+use std::io;
+use std::os::unix::io::RawFd;
+
+// Chunk size for each splice(2) call, matching uutils' cat.
+const CHUNK_SIZE: usize = 128 * 1024;
+
+// try_splice copies as much as it can from `input` to `output` using
+// splice(2), routing bytes through an internal pipe (splice can only
+// move data directly between two descriptors when at least one of them
+// is a pipe). Returns `Ok(true)` once `input` has been copied through
+// to EOF, or `Ok(false)` if the kernel rejects splicing this pair of
+// descriptors (`EINVAL`/`ENOSYS` — this happens for, e.g., a file
+// opened with `O_APPEND`), in which case `input`'s read position is
+// left wherever splice got to and the caller should finish the copy
+// with `io::copy`.
+pub fn try_splice(input: RawFd, output: RawFd) -> io::Result<bool> {
+    let pipe = match Pipe::new() {
+        Ok(pipe) => pipe,
+        Err(e) if is_unsupported(&e) => return Ok(false),
+        Err(e) => return Err(e),
+    };
+
+    loop {
+        let n = match splice(input, pipe.write, CHUNK_SIZE) {
+            Ok(0) => return Ok(true),
+            Ok(n) => n,
+            Err(e) if is_unsupported(&e) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let mut remaining = n;
+        while remaining > 0 {
+            match splice(pipe.read, output, remaining) {
+                Ok(0) => break,
+                Ok(moved) => remaining -= moved,
+                Err(e) if is_unsupported(&e) => {
+                    // We've already pulled `remaining` bytes out of
+                    // `input` and into our pipe, so draining the pipe
+                    // with plain read/write is the only way not to
+                    // lose them. `input`'s position has moved on by
+                    // exactly the bytes already spliced out of it, so
+                    // the caller's io::copy fallback picks up cleanly
+                    // from there.
+                    drain_pipe(pipe.read, output, remaining)?;
+                    return Ok(false);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+// drain_pipe copies `len` bytes sitting in `pipe_read` to `output`
+// using plain read/write, for when splice can't write to `output`.
+fn drain_pipe(pipe_read: RawFd, output: RawFd, mut len: usize) -> io::Result<()> {
+    let mut buf = vec![0u8; CHUNK_SIZE.min(len.max(1))];
+    while len > 0 {
+        let want = len.min(buf.len());
+        let n = read(pipe_read, &mut buf[..want])?;
+        if n == 0 {
+            break;
+        }
+        write_all(output, &buf[..n])?;
+        len -= n;
+    }
+    Ok(())
+}
+
+fn read(fd: RawFd, buf: &mut [u8]) -> io::Result<usize> {
+    let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+fn write_all(fd: RawFd, mut buf: &[u8]) -> io::Result<()> {
+    while !buf.is_empty() {
+        let n = unsafe { libc::write(fd, buf.as_ptr() as *const libc::c_void, buf.len()) };
+        if n < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        buf = &buf[n as usize..];
+    }
+    Ok(())
+}
+
+// Pipe owns both ends of an anonymous pipe, used as splice's mandatory
+// intermediate buffer, and closes them on drop.
+struct Pipe {
+    read: RawFd,
+    write: RawFd,
+}
+
+impl Pipe {
+    fn new() -> io::Result<Pipe> {
+        let mut fds = [0 as RawFd; 2];
+        if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Pipe {
+            read: fds[0],
+            write: fds[1],
+        })
+    }
+}
+
+impl Drop for Pipe {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.read);
+            libc::close(self.write);
+        }
+    }
+}
+
+// splice moves up to `len` bytes from `from` to `to`, both at their
+// current file offsets, and returns how many bytes actually moved.
+fn splice(from: RawFd, to: RawFd, len: usize) -> io::Result<usize> {
+    let n = unsafe {
+        libc::splice(
+            from,
+            std::ptr::null_mut(),
+            to,
+            std::ptr::null_mut(),
+            len,
+            libc::SPLICE_F_MOVE,
+        )
+    };
+    if n < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(n as usize)
+}
+
+fn is_unsupported(e: &io::Error) -> bool {
+    matches!(e.raw_os_error(), Some(libc::EINVAL) | Some(libc::ENOSYS))
+}